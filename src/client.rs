@@ -0,0 +1,237 @@
+//! Client-side mirror of [`crate::codec::HttpCodec`]: encodes requests and
+//! decodes responses, so the same `HttpFramed` transport can drive outbound
+//! HTTP calls over a `TcpStream`.
+//!
+//! Nothing in this binary calls out over HTTP yet, so none of this is
+//! reachable from `main`. It's kept here (rather than left unwritten) as
+//! the client half of the framing machinery for whoever adds an outbound
+//! call path next; until then, silence the otherwise-legitimate
+//! `dead_code` warning rather than wiring in a call site just to satisfy
+//! the lint.
+#![allow(dead_code)]
+
+use std::io;
+
+use bytes::BytesMut;
+use http::{HeaderValue, Method, Request, Response, Uri, Version};
+
+use crate::codec::{
+    content_length, decode_chunked, drain_sized_body, is_chunked, BodyKind, BodyLen, BoxBody,
+    ChunkState, Decoder, Encoder, MessageBody,
+};
+
+#[derive(Default)]
+pub struct ClientCodec {
+    state: DecodeState,
+}
+
+#[derive(Default)]
+enum DecodeState {
+    #[default]
+    Head,
+    Body(PendingResponse),
+}
+
+struct PendingResponse {
+    parts: http::response::Parts,
+    body_len: BodyLen,
+    chunk_state: ChunkState,
+    body: Vec<u8>,
+}
+
+impl<B> Encoder<Request<B>> for ClientCodec
+where
+    B: MessageBody + Send + 'static,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, request: Request<B>, dst: &mut BytesMut) -> Result<Option<BoxBody>, Self::Error> {
+        let method = request.method().clone();
+        let version = request.version();
+        let path = request
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string());
+        let kind = request.body().kind();
+
+        let request_line = format!("{} {} {:?}\r\n", method, path, version);
+        dst.extend_from_slice(request_line.as_bytes());
+
+        match kind {
+            BodyKind::Empty => {}
+            BodyKind::Sized(len) => {
+                dst.extend_from_slice(format!("Content-Length: {}\r\n", len).as_bytes())
+            }
+            BodyKind::Streaming => dst.extend_from_slice(b"Transfer-Encoding: chunked\r\n"),
+        }
+
+        for (key, value) in request.headers() {
+            let header = format!(
+                "{}: {}\r\n",
+                key.as_str(),
+                value.to_str().unwrap_or_else(|_| "")
+            );
+            dst.extend_from_slice(header.as_bytes());
+        }
+        // end of headers
+        dst.extend_from_slice(b"\r\n");
+
+        let body = request.into_body();
+
+        match kind {
+            BodyKind::Empty => Ok(None),
+            BodyKind::Sized(_) => {
+                dst.extend_from_slice(&drain_sized_body(body)?);
+                Ok(None)
+            }
+            BodyKind::Streaming => Ok(Some(Box::pin(body))),
+        }
+    }
+}
+
+impl Decoder for ClientCodec {
+    type Item = Response<Vec<u8>>;
+
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match &mut self.state {
+                DecodeState::Head => {
+                    let Some(parts) = decode_head(src)? else {
+                        return Ok(None);
+                    };
+
+                    let chunked = is_chunked(&parts.headers);
+                    let length = content_length(&parts.headers)?;
+
+                    if chunked && length.is_some() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            "conflicting Content-Length and Transfer-Encoding",
+                        ));
+                    }
+
+                    let body_len = if chunked {
+                        BodyLen::Chunked
+                    } else {
+                        BodyLen::Sized(length.unwrap_or(0))
+                    };
+
+                    self.state = DecodeState::Body(PendingResponse {
+                        parts,
+                        body_len,
+                        chunk_state: ChunkState::Size,
+                        body: Vec::new(),
+                    });
+                }
+                DecodeState::Body(pending) => {
+                    let complete = match pending.body_len {
+                        BodyLen::Sized(len) => {
+                            if src.len() < len {
+                                false
+                            } else {
+                                pending.body = src.split_to(len).to_vec();
+                                true
+                            }
+                        }
+                        BodyLen::Chunked => {
+                            decode_chunked(src, &mut pending.chunk_state, &mut pending.body)?
+                        }
+                    };
+
+                    if !complete {
+                        return Ok(None);
+                    }
+
+                    let DecodeState::Body(pending) =
+                        std::mem::replace(&mut self.state, DecodeState::Head)
+                    else {
+                        unreachable!()
+                    };
+
+                    return Ok(Some(Response::from_parts(pending.parts, pending.body)));
+                }
+            }
+        }
+    }
+}
+
+fn decode_head(src: &mut BytesMut) -> Result<Option<http::response::Parts>, io::Error> {
+    let mut parsed_headers = [httparse::EMPTY_HEADER; 64];
+    let mut r = httparse::Response::new(&mut parsed_headers);
+    let status = r
+        .parse(src)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+    let offset = match status {
+        httparse::Status::Complete(offset) => offset,
+        httparse::Status::Partial => return Ok(None),
+    };
+
+    let version = match r.version {
+        Some(0) => Version::HTTP_10,
+        Some(1) => Version::HTTP_11,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "version not supported",
+            ))
+        }
+    };
+
+    let code = r
+        .code
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing status code"))?;
+
+    let mut builder = Response::builder().status(code).version(version);
+
+    for header in r.headers.iter() {
+        builder = builder.header(header.name, header.value);
+    }
+
+    let _ = src.split_to(offset);
+
+    let (parts, ()) = builder
+        .body(())
+        .map_err(|op| io::Error::new(io::ErrorKind::Other, op))?
+        .into_parts();
+
+    Ok(Some(parts))
+}
+
+/// A small `http::request::Builder` wrapper that fills in `Host` (from the
+/// URI) and a default `User-Agent`, so callers driving outbound calls
+/// through [`ClientCodec`] don't have to set them by hand.
+pub struct ClientRequest {
+    builder: http::request::Builder,
+}
+
+impl ClientRequest {
+    pub fn new(method: Method, uri: Uri) -> Self {
+        let host = match (uri.host(), uri.port_u16()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => String::new(),
+        };
+
+        let builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .version(Version::HTTP_11)
+            .header(http::header::HOST, host)
+            .header(http::header::USER_AGENT, "dirtio-http");
+
+        Self { builder }
+    }
+
+    pub fn header(mut self, key: &str, value: impl Into<HeaderValue>) -> Self {
+        self.builder = self.builder.header(key, value.into());
+        self
+    }
+
+    pub fn body<B: MessageBody>(self, body: B) -> Result<Request<B>, http::Error> {
+        self.builder.body(body)
+    }
+}
@@ -1,12 +1,92 @@
 use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use http::{Request, Response, Version};
 
 pub trait Encoder<Item> {
     type Error: From<io::Error>;
 
-    fn encode(&mut self, item: Item, dst: &mut BytesMut) -> Result<(), Self::Error>;
+    /// Encode `item` into `dst`. When the item carries a body that can't be
+    /// written in one shot (a [`BodyKind::Streaming`] body), the body is
+    /// handed back so the caller can drive it across further write cycles
+    /// instead of blocking `encode` on it.
+    fn encode(&mut self, item: Item, dst: &mut BytesMut) -> Result<Option<BoxBody>, Self::Error>;
+}
+
+/// How large a [`MessageBody`] is, so the encoder knows whether to frame it
+/// with `Content-Length` or `Transfer-Encoding: chunked`.
+pub enum BodyKind {
+    Empty,
+    Sized(usize),
+    Streaming,
+}
+
+/// A request or response body that may be fully buffered already or produced
+/// incrementally (e.g. a streamed file or generated content).
+///
+/// Bodies reporting [`BodyKind::Empty`] or [`BodyKind::Sized`] must resolve
+/// `poll_data` immediately, since the encoder drains them synchronously to
+/// fill in `Content-Length`.
+pub trait MessageBody: Unpin {
+    fn kind(&self) -> BodyKind;
+
+    fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>>;
+}
+
+/// A type-erased [`MessageBody`], used once a streaming body has outlived
+/// the `encode` call that produced it.
+pub type BoxBody = Pin<Box<dyn MessageBody + Send>>;
+
+impl MessageBody for Vec<u8> {
+    fn kind(&self) -> BodyKind {
+        if self.is_empty() {
+            BodyKind::Empty
+        } else {
+            BodyKind::Sized(self.len())
+        }
+    }
+
+    fn poll_data(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        if self.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(Ok(Bytes::from(std::mem::take(&mut *self)))))
+        }
+    }
+}
+
+impl MessageBody for BoxBody {
+    fn kind(&self) -> BodyKind {
+        self.as_ref().get_ref().kind()
+    }
+
+    fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        self.get_mut().as_mut().poll_data(cx)
+    }
+}
+
+/// Drains a [`BodyKind::Empty`]/[`BodyKind::Sized`] body into a `Vec<u8>`.
+/// Panics if the body doesn't resolve immediately, which such bodies must.
+pub(crate) fn drain_sized_body<B: MessageBody>(mut body: B) -> io::Result<Vec<u8>> {
+    let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+    let mut out = Vec::new();
+    loop {
+        match Pin::new(&mut body).poll_data(&mut cx) {
+            Poll::Ready(Some(Ok(chunk))) => out.extend_from_slice(&chunk),
+            Poll::Ready(Some(Err(e))) => return Err(e),
+            Poll::Ready(None) => return Ok(out),
+            Poll::Pending => unreachable!("sized bodies must resolve immediately"),
+        }
+    }
+}
+
+/// Frames `data` as a single chunk of a `Transfer-Encoding: chunked` body.
+pub(crate) fn encode_chunk(dst: &mut BytesMut, data: &[u8]) {
+    dst.extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+    dst.extend_from_slice(data);
+    dst.extend_from_slice(b"\r\n");
 }
 
 pub trait Decoder {
@@ -30,32 +110,107 @@ pub trait Decoder {
     }
 }
 
-pub struct HttpCodec;
+pub struct HttpCodec {
+    state: DecodeState,
+    /// Version of the most recently decoded request, echoed in the next
+    /// response's status line.
+    version: Version,
+    /// Whether the connection should persist after the next response,
+    /// negotiated from the most recently decoded request's version and
+    /// `Connection` header.
+    keep_alive: bool,
+}
+
+impl Default for HttpCodec {
+    fn default() -> Self {
+        Self {
+            state: DecodeState::default(),
+            version: Version::HTTP_11,
+            keep_alive: true,
+        }
+    }
+}
+
+impl HttpCodec {
+    /// Whether the connection should stay open after the in-flight
+    /// response, per the most recently decoded request.
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+}
+
+#[derive(Default)]
+enum DecodeState {
+    #[default]
+    Head,
+    Body(PendingRequest),
+}
 
-impl Encoder<Response<Vec<u8>>> for HttpCodec {
+struct PendingRequest {
+    parts: http::request::Parts,
+    body_len: BodyLen,
+    chunk_state: ChunkState,
+    body: Vec<u8>,
+}
+
+/// Shared with [`crate::client::ClientCodec`], which decodes responses
+/// using the same `Content-Length`/chunked body framing.
+pub(crate) enum BodyLen {
+    Sized(usize),
+    Chunked,
+}
+
+pub(crate) enum ChunkState {
+    Size,
+    Data(usize),
+    Trailer,
+}
+
+impl<B> Encoder<Response<B>> for HttpCodec
+where
+    B: MessageBody + Send + 'static,
+{
     type Error = io::Error;
 
-    fn encode(
-        &mut self,
-        response: Response<Vec<u8>>,
-        dst: &mut BytesMut,
-    ) -> Result<(), Self::Error> {
+    fn encode(&mut self, response: Response<B>, dst: &mut BytesMut) -> Result<Option<BoxBody>, Self::Error> {
         let status = response.status();
+        let kind = response.body().kind();
 
         let status_line = format!(
             "\
-            HTTP/1.1 {} {}\r\n\
+            {:?} {} {}\r\n\
             Server: dirtio-http\r\n\
             Date: {}\r\n\
-            Content-Length: {}\r\n\
             ",
+            self.version,
             status.as_str(),
             status.canonical_reason().unwrap(),
             httpdate::fmt_http_date(std::time::SystemTime::now()),
-            response.body().len(),
         );
         dst.extend_from_slice(status_line.as_bytes());
 
+        match kind {
+            BodyKind::Empty => dst.extend_from_slice(b"Content-Length: 0\r\n"),
+            BodyKind::Sized(len) => {
+                dst.extend_from_slice(format!("Content-Length: {}\r\n", len).as_bytes())
+            }
+            BodyKind::Streaming => dst.extend_from_slice(b"Transfer-Encoding: chunked\r\n"),
+        }
+
+        // Only inject the negotiated `Connection` header when the response
+        // doesn't already carry one (e.g. a `101 Switching Protocols`
+        // handshake sets its own `Connection: Upgrade`, which must reach
+        // the client untouched).
+        if !response.headers().contains_key(http::header::CONNECTION) {
+            dst.extend_from_slice(
+                format!(
+                    "Connection: {}\r\n",
+                    if self.keep_alive { "keep-alive" } else { "close" }
+                )
+                .as_bytes(),
+            );
+        }
+
         for (key, value) in response.headers() {
             let header = format!(
                 "{}: {}\r\n",
@@ -67,19 +222,100 @@ impl Encoder<Response<Vec<u8>>> for HttpCodec {
         // end of headers
         dst.extend_from_slice(b"\r\n");
 
-        dst.extend_from_slice(&response.into_body());
+        let mut body = Box::pin(response.into_body());
 
-        Ok(())
+        match kind {
+            BodyKind::Empty => Ok(None),
+            BodyKind::Sized(_) => {
+                let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+                match body.as_mut().poll_data(&mut cx) {
+                    Poll::Ready(Some(Ok(chunk))) => {
+                        dst.extend_from_slice(&chunk);
+                        Ok(None)
+                    }
+                    Poll::Ready(Some(Err(e))) => Err(e),
+                    Poll::Ready(None) => Ok(None),
+                    Poll::Pending => {
+                        unreachable!("BodyKind::Sized bodies must resolve immediately")
+                    }
+                }
+            }
+            BodyKind::Streaming => Ok(Some(body)),
+        }
     }
 }
 
 impl Decoder for HttpCodec {
-    type Item = Request<()>;
+    type Item = Request<Vec<u8>>;
 
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let mut request = Request::builder();
+        loop {
+            match &mut self.state {
+                DecodeState::Head => {
+                    let Some(parts) = self.decode_head(src)? else {
+                        return Ok(None);
+                    };
+
+                    let chunked = is_chunked(&parts.headers);
+                    let length = content_length(&parts.headers)?;
+
+                    if chunked && length.is_some() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            "conflicting Content-Length and Transfer-Encoding",
+                        ));
+                    }
+
+                    let body_len = if chunked {
+                        BodyLen::Chunked
+                    } else {
+                        BodyLen::Sized(length.unwrap_or(0))
+                    };
+
+                    self.state = DecodeState::Body(PendingRequest {
+                        parts,
+                        body_len,
+                        chunk_state: ChunkState::Size,
+                        body: Vec::new(),
+                    });
+                }
+                DecodeState::Body(pending) => {
+                    let complete = match pending.body_len {
+                        BodyLen::Sized(len) => {
+                            if src.len() < len {
+                                false
+                            } else {
+                                pending.body = src.split_to(len).to_vec();
+                                true
+                            }
+                        }
+                        BodyLen::Chunked => {
+                            decode_chunked(src, &mut pending.chunk_state, &mut pending.body)?
+                        }
+                    };
+
+                    if !complete {
+                        return Ok(None);
+                    }
+
+                    let DecodeState::Body(pending) =
+                        std::mem::replace(&mut self.state, DecodeState::Head)
+                    else {
+                        unreachable!()
+                    };
+
+                    return Ok(Some(Request::from_parts(pending.parts, pending.body)));
+                }
+            }
+        }
+    }
+}
+
+impl HttpCodec {
+    fn decode_head(&mut self, src: &mut BytesMut) -> Result<Option<http::request::Parts>, io::Error> {
+        let mut builder = Request::builder();
         let mut parsed_headers = [httparse::EMPTY_HEADER; 64];
         let mut r = httparse::Request::new(&mut parsed_headers);
         let status = r
@@ -91,28 +327,134 @@ impl Decoder for HttpCodec {
             httparse::Status::Partial => return Ok(None),
         };
 
-        // Only support HTTP 1.1
-        if r.version != Some(1) {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "version not supported",
-            ));
-        }
+        let version = match r.version {
+            Some(0) => Version::HTTP_10,
+            Some(1) => Version::HTTP_11,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "version not supported",
+                ))
+            }
+        };
 
-        request = request.method(r.method.unwrap());
-        request = request.uri(r.path.unwrap());
-        request = request.version(Version::HTTP_11);
+        builder = builder.method(r.method.unwrap());
+        builder = builder.uri(r.path.unwrap());
+        builder = builder.version(version);
 
         for header in r.headers.iter() {
-            request = request.header(header.name, header.value);
+            builder = builder.header(header.name, header.value);
         }
 
         let _ = src.split_to(offset);
 
-        Ok(Some(
-            request
-                .body(())
-                .map_err(|op| io::Error::new(io::ErrorKind::Other, op))?,
-        ))
+        let (parts, ()) = builder
+            .body(())
+            .map_err(|op| io::Error::new(io::ErrorKind::Other, op))?
+            .into_parts();
+
+        // HTTP/1.1 defaults to persistent connections unless told to close;
+        // HTTP/1.0 is the other way around.
+        self.keep_alive = match version {
+            Version::HTTP_11 => !header_has_token(&parts.headers, http::header::CONNECTION, "close"),
+            _ => header_has_token(&parts.headers, http::header::CONNECTION, "keep-alive"),
+        };
+        self.version = version;
+
+        Ok(Some(parts))
     }
 }
+
+/// Case-insensitive, comma-token-aware check for whether `headers[name]`
+/// contains `token` (e.g. matching `keep-alive` in `Connection: keep-alive,
+/// Upgrade`).
+fn header_has_token(headers: &http::HeaderMap, name: impl http::header::AsHeaderName, token: &str) -> bool {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(',')
+                .any(|candidate| candidate.trim().eq_ignore_ascii_case(token))
+        })
+        .unwrap_or(false)
+}
+
+pub(crate) fn is_chunked(headers: &http::HeaderMap) -> bool {
+    header_has_token(headers, http::header::TRANSFER_ENCODING, "chunked")
+}
+
+pub(crate) fn content_length(headers: &http::HeaderMap) -> Result<Option<usize>, io::Error> {
+    let Some(value) = headers.get(http::header::CONTENT_LENGTH) else {
+        return Ok(None);
+    };
+
+    let value = value
+        .to_str()
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid Content-Length"))?;
+
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid Content-Length"))
+}
+
+/// Decodes a chunked body into `body`, returning `true` once the
+/// terminating zero-size chunk and trailer have been consumed. Tolerates a
+/// partial chunk split across reads: complete chunks are consumed from
+/// `src`, an incomplete one is left in place, and `false` is returned when
+/// more data is needed.
+pub(crate) fn decode_chunked(
+    src: &mut BytesMut,
+    chunk_state: &mut ChunkState,
+    body: &mut Vec<u8>,
+) -> Result<bool, io::Error> {
+    loop {
+        match *chunk_state {
+            ChunkState::Size => {
+                let Some(pos) = find_crlf(src) else {
+                    return Ok(false);
+                };
+                let line = src.split_to(pos);
+                let _ = src.split_to(2); // consume the size line's \r\n
+
+                let line = std::str::from_utf8(&line)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid chunk size"))?;
+                // Ignore chunk extensions (`size;ext=value`).
+                let size = line.split(';').next().unwrap_or("").trim();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid chunk size"))?;
+
+                *chunk_state = if size == 0 {
+                    ChunkState::Trailer
+                } else {
+                    ChunkState::Data(size)
+                };
+            }
+            ChunkState::Data(remaining) => {
+                // Data plus its trailing CRLF.
+                if src.len() < remaining + 2 {
+                    return Ok(false);
+                }
+                body.extend_from_slice(&src.split_to(remaining));
+                let _ = src.split_to(2);
+                *chunk_state = ChunkState::Size;
+            }
+            ChunkState::Trailer => {
+                let Some(pos) = find_crlf(src) else {
+                    return Ok(false);
+                };
+                if pos == 0 {
+                    let _ = src.split_to(2);
+                    return Ok(true);
+                }
+                // Discard trailer headers; nothing in this server consumes them.
+                let _ = src.split_to(pos + 2);
+            }
+        }
+    }
+}
+
+fn find_crlf(src: &[u8]) -> Option<usize> {
+    src.windows(2).position(|w| w == b"\r\n")
+}
@@ -0,0 +1,233 @@
+//! Response compression, negotiated from the request's `Accept-Encoding`
+//! and applied in the encode path via a [`MessageBody`] adapter.
+
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http::{HeaderMap, HeaderValue, Response};
+
+use crate::codec::{drain_sized_body, BodyKind, BoxBody, MessageBody};
+
+/// `Sized` bodies smaller than this are left uncompressed. `Streaming`
+/// bodies have no known size up front and are compressed regardless.
+pub const DEFAULT_THRESHOLD: usize = 860;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Picks the best encoding the client advertised in `Accept-Encoding`,
+/// preferring `br` over `gzip`.
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let value = headers.get(http::header::ACCEPT_ENCODING)?.to_str().ok()?;
+    let offers = value.split(',').map(|t| t.trim());
+
+    if offers.clone().any(|t| t.eq_ignore_ascii_case("br")) {
+        Some(Encoding::Brotli)
+    } else if offers.clone().any(|t| t.eq_ignore_ascii_case("gzip")) {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+fn is_compressible<B>(response: &Response<B>) -> bool {
+    if response.headers().contains_key(http::header::CONTENT_ENCODING) {
+        return false;
+    }
+
+    // A 206 (or any response already carrying `Content-Range`) is a byte
+    // slice of the full resource; compressing it would desync its body
+    // length from the offsets advertised in `Content-Range`.
+    if response.status() == http::StatusCode::PARTIAL_CONTENT
+        || response.headers().contains_key(http::header::CONTENT_RANGE)
+    {
+        return false;
+    }
+
+    match response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(content_type) => {
+            let content_type = content_type.split(';').next().unwrap_or("").trim();
+            !matches!(
+                content_type,
+                "image/jpeg"
+                    | "image/png"
+                    | "image/gif"
+                    | "image/webp"
+                    | "video/mp4"
+                    | "application/zip"
+                    | "application/gzip"
+            )
+        }
+        None => true,
+    }
+}
+
+/// Compresses `response`'s body with the encoding negotiated from `headers`
+/// when it's eligible: not already encoded, not an already-compressed media
+/// type, and (for `Sized` bodies) at least `threshold` bytes. `Sized`
+/// bodies are buffered and compressed up front so `Content-Length` still
+/// reflects the real size; `Streaming` bodies are wrapped so chunks are
+/// compressed as they're produced, whatever their total size.
+pub fn compress_response<B>(
+    request_headers: &HeaderMap,
+    response: Response<B>,
+    threshold: usize,
+) -> Response<BoxBody>
+where
+    B: MessageBody + Send + 'static,
+{
+    let Some(encoding) = negotiate(request_headers) else {
+        return response.map(|body| Box::pin(body) as BoxBody);
+    };
+
+    if !is_compressible(&response) {
+        return response.map(|body| Box::pin(body) as BoxBody);
+    }
+
+    match response.body().kind() {
+        BodyKind::Empty => response.map(|body| Box::pin(body) as BoxBody),
+        BodyKind::Sized(len) if len < threshold => response.map(|body| Box::pin(body) as BoxBody),
+        BodyKind::Sized(_) => {
+            let (mut parts, body) = response.into_parts();
+            let data = match drain_sized_body(body) {
+                Ok(data) => data,
+                Err(_) => return Response::from_parts(parts, Box::pin(Vec::new()) as BoxBody),
+            };
+
+            match encode_all(encoding, &data) {
+                Ok(compressed) => {
+                    parts.headers.insert(
+                        http::header::CONTENT_ENCODING,
+                        HeaderValue::from_static(encoding.token()),
+                    );
+                    Response::from_parts(parts, Box::pin(compressed) as BoxBody)
+                }
+                Err(_) => Response::from_parts(parts, Box::pin(data) as BoxBody),
+            }
+        }
+        BodyKind::Streaming => {
+            let (mut parts, body) = response.into_parts();
+            parts.headers.insert(
+                http::header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.token()),
+            );
+            let compressed = CompressedBody {
+                inner: body,
+                encoder: Some(StreamEncoder::new(encoding)),
+                finished: false,
+            };
+            Response::from_parts(parts, Box::pin(compressed) as BoxBody)
+        }
+    }
+}
+
+fn encode_all(encoding: Encoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = StreamEncoder::new(encoding);
+    let mut out = encoder.write(data)?;
+    out.extend(encoder.finish()?);
+    Ok(out)
+}
+
+/// A [`MessageBody`] adapter that compresses an inner streaming body chunk
+/// by chunk, for `Transfer-Encoding: chunked` responses whose final size
+/// isn't known up front.
+struct CompressedBody<B> {
+    inner: B,
+    encoder: Option<StreamEncoder>,
+    finished: bool,
+}
+
+impl<B: MessageBody> MessageBody for CompressedBody<B> {
+    fn kind(&self) -> BodyKind {
+        BodyKind::Streaming
+    }
+
+    fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        let this = self.get_mut();
+
+        if this.finished {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match Pin::new(&mut this.inner).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    match this.encoder.as_mut().unwrap().write(&chunk) {
+                        Ok(out) if !out.is_empty() => return Poll::Ready(Some(Ok(out.into()))),
+                        Ok(_) => continue,
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    this.finished = true;
+                    return match this.encoder.take().unwrap().finish() {
+                        Ok(out) if !out.is_empty() => Poll::Ready(Some(Ok(out.into()))),
+                        Ok(_) => Poll::Ready(None),
+                        Err(e) => Poll::Ready(Some(Err(e))),
+                    };
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+enum StreamEncoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+impl StreamEncoder {
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Gzip => StreamEncoder::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+            Encoding::Brotli => {
+                StreamEncoder::Brotli(Box::new(brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22)))
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            StreamEncoder::Gzip(enc) => {
+                enc.write_all(data)?;
+                Ok(std::mem::take(enc.get_mut()))
+            }
+            StreamEncoder::Brotli(enc) => {
+                enc.write_all(data)?;
+                enc.flush()?;
+                Ok(std::mem::take(enc.get_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            StreamEncoder::Gzip(enc) => enc.finish(),
+            // `flush` alone leaves the brotli stream open; `into_inner`
+            // writes the final block before handing the buffer back.
+            StreamEncoder::Brotli(enc) => Ok((*enc).into_inner()),
+        }
+    }
+}
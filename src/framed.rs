@@ -1,9 +1,9 @@
-use crate::codec::{Decoder, Encoder};
+use crate::codec::{encode_chunk, BoxBody, Decoder, Encoder};
 
 use std::io;
 use std::task::{ready, Poll};
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, BufMut, BytesMut};
 use dirtio::net::tcp::TcpStream;
 use futures::{AsyncRead, AsyncWrite, Sink, Stream};
 use pin_project_lite::pin_project;
@@ -25,6 +25,21 @@ impl<C> HttpFramed<C> {
             state: RWFrame::default(),
         }
     }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Replaces the codec in place, e.g. after a protocol upgrade, while
+    /// preserving the underlying stream and any buffered bytes (such as
+    /// pipelined data already read past the upgrade response).
+    pub fn into_codec<C2>(self, codec: C2) -> HttpFramed<C2> {
+        HttpFramed {
+            inner: self.inner,
+            codec,
+            state: self.state,
+        }
+    }
 }
 
 impl<C> Stream for HttpFramed<C>
@@ -63,17 +78,34 @@ where
                 state.readable = false;
             }
 
-            // A dummy implementation.
-            //
-            // Read to a tmp buffer first, and then
-            // copy to the main buffer, may be slow
-            // to have an extra copy.
-            let n = ready!(this.inner.as_mut().poll_read(cx, &mut state.tmp_buf))?;
+            // Read straight into the buffer's spare capacity instead of a
+            // tmp buffer plus a copy.
+            state.buffer.reserve(state.reserved);
+
+            let n = {
+                let dst = state.buffer.chunk_mut();
+                let len = dst.len().min(state.reserved);
+                // Safety: `u8` has no invalid bit patterns, so handing
+                // `poll_read` a slice over `BytesMut`'s uninitialized spare
+                // capacity is sound; we only trust the `n` bytes it
+                // reports having written before calling `advance_mut`.
+                let dst = unsafe { std::slice::from_raw_parts_mut(dst.as_mut_ptr(), len) };
+                ready!(this.inner.as_mut().poll_read(cx, dst))?
+            };
 
             if n == 0 {
                 state.eof = true;
             } else {
-                state.buffer.extend_from_slice(&state.tmp_buf[0..n]);
+                // Safety: `poll_read` just initialized the first `n` bytes
+                // of the region we handed it.
+                unsafe { state.buffer.advance_mut(n) };
+
+                // Keep doubling the reserved region (up to a cap) as long
+                // as reads keep filling it, so large requests/bodies don't
+                // pay for repeated small reservations.
+                if n >= state.reserved {
+                    state.reserved = (state.reserved * 2).min(MAX_RESERVED);
+                }
             }
 
             state.readable = true;
@@ -104,7 +136,8 @@ where
     fn start_send(self: std::pin::Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
         let this = self.project();
         let state = &mut this.state.write;
-        this.codec.encode(item, &mut state.buffer)
+        state.body = this.codec.encode(item, &mut state.buffer)?;
+        Ok(())
     }
 
     fn poll_flush(
@@ -114,16 +147,37 @@ where
         let mut this = self.project();
         let state = &mut this.state.write;
 
-        while !state.buffer.is_empty() {
-            let n = ready!(this.inner.as_mut().poll_write(cx, &state.buffer.chunk())?);
-            state.buffer.advance(n);
+        loop {
+            while !state.buffer.is_empty() {
+                let n = ready!(this.inner.as_mut().poll_write(cx, &state.buffer.chunk())?);
+                state.buffer.advance(n);
+
+                if n == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write to stream",
+                    )
+                    .into()));
+                }
+            }
 
-            if n == 0 {
-                return Poll::Ready(Err(io::Error::new(
-                    io::ErrorKind::WriteZero,
-                    "failed to write to stream",
-                )
-                .into()));
+            // Drive any streaming body left over from `start_send`,
+            // framing each produced chunk before writing it out above.
+            let Some(body) = state.body.as_mut() else {
+                break;
+            };
+
+            match body.as_mut().poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => encode_chunk(&mut state.buffer, &chunk),
+                Poll::Ready(Some(Err(e))) => {
+                    state.body = None;
+                    return Poll::Ready(Err(e.into()));
+                }
+                Poll::Ready(None) => {
+                    state.buffer.extend_from_slice(b"0\r\n\r\n");
+                    state.body = None;
+                }
+                Poll::Pending => return Poll::Pending,
             }
         }
 
@@ -147,6 +201,7 @@ where
 }
 
 const INITIAL_CAPACITY: usize = 8 * 1024;
+const MAX_RESERVED: usize = 1024 * 1024;
 
 #[derive(Default)]
 struct RWFrame {
@@ -158,12 +213,16 @@ struct ReadFrame {
     eof: bool,
     readable: bool,
     buffer: BytesMut,
-    tmp_buf: [u8; INITIAL_CAPACITY],
+    /// How much spare capacity to reserve before each read; grows when a
+    /// read keeps filling the reserved region entirely.
+    reserved: usize,
 }
 
 struct WriteFrame {
     backpressure_boundary: usize,
     buffer: BytesMut,
+    /// A streaming body still being drained across `poll_flush` cycles.
+    body: Option<BoxBody>,
 }
 
 impl Default for ReadFrame {
@@ -172,7 +231,7 @@ impl Default for ReadFrame {
             eof: false,
             readable: false,
             buffer: BytesMut::with_capacity(INITIAL_CAPACITY),
-            tmp_buf: [0u8; INITIAL_CAPACITY],
+            reserved: INITIAL_CAPACITY,
         }
     }
 }
@@ -182,6 +241,7 @@ impl Default for WriteFrame {
         Self {
             backpressure_boundary: INITIAL_CAPACITY,
             buffer: BytesMut::with_capacity(INITIAL_CAPACITY),
+            body: None,
         }
     }
 }
@@ -1,8 +1,12 @@
+mod client;
 mod codec;
+mod compress;
 mod framed;
+mod ws;
 
 use codec::HttpCodec;
 use framed::HttpFramed;
+use ws::WsCodec;
 
 use std::env;
 use std::error::Error;
@@ -39,20 +43,24 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 async fn process(stream: TcpStream) -> Result<(), Box<dyn Error>> {
-    let mut transport = HttpFramed::new(stream, HttpCodec);
+    let mut transport = HttpFramed::new(stream, HttpCodec::default());
 
     while let Some(item) = transport.next().await {
         match item {
             Ok(request) => {
+                if ws::is_upgrade_request(&request) {
+                    return upgrade_to_websocket(transport, &request).await;
+                }
+
                 let response = respond(&request)?;
+                let response = compress::compress_response(
+                    request.headers(),
+                    response,
+                    compress::DEFAULT_THRESHOLD,
+                );
                 transport.send(response).await?;
 
-                // Client may want to close connection.
-                if let Some("close") = request
-                    .headers()
-                    .get("Connection")
-                    .and_then(|v| v.to_str().ok())
-                {
+                if !transport.codec().keep_alive() {
                     break;
                 }
             }
@@ -64,7 +72,51 @@ async fn process(stream: TcpStream) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn respond(req: &Request<()>) -> Result<Response<Vec<u8>>, Box<dyn Error>> {
+async fn upgrade_to_websocket(
+    mut transport: HttpFramed<HttpCodec>,
+    request: &Request<Vec<u8>>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(key) = request
+        .headers()
+        .get("Sec-WebSocket-Key")
+        .and_then(|v| v.to_str().ok())
+    else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Vec::new())?;
+        transport.send(response).await?;
+        return Ok(());
+    };
+
+    let response = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", ws::accept_key(key))
+        .body(Vec::new())?;
+    transport.send(response).await?;
+
+    let mut transport = transport.into_codec(WsCodec::default());
+
+    while let Some(item) = transport.next().await {
+        match item? {
+            ws::Message::Close => {
+                transport.send(ws::Message::Close).await?;
+                break;
+            }
+            ws::Message::Ping(payload) => {
+                transport.send(ws::Message::Pong(payload)).await?;
+            }
+            ws::Message::Pong(_) => {}
+            message => {
+                transport.send(message).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn respond(req: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, Box<dyn Error>> {
     let mut response = Response::builder();
 
     // Normalize path to prevent path traversal attack.
@@ -83,13 +135,116 @@ fn respond(req: &Request<()>) -> Result<Response<Vec<u8>>, Box<dyn Error>> {
         path.push("index.html");
     }
 
-    let body = match fs::read(path) {
-        Ok(content) => content,
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
         Err(_) => {
-            response = response.status(StatusCode::NOT_FOUND);
-            Vec::new()
+            return Ok(response.status(StatusCode::NOT_FOUND).body(Vec::new())?);
+        }
+    };
+
+    let modified = metadata.modified()?;
+    let etag = file_etag(&metadata, modified);
+    let last_modified = httpdate::fmt_http_date(modified);
+
+    if is_not_modified(req, &etag, modified) {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .header("ETag", etag)
+            .header("Last-Modified", last_modified)
+            .body(Vec::new())?);
+    }
+
+    let content = fs::read(&path)?;
+    response = response
+        .header("ETag", etag)
+        .header("Last-Modified", last_modified);
+
+    let range = match req.headers().get("Range").and_then(|v| v.to_str().ok()) {
+        Some(range) => range,
+        None => return Ok(response.body(content)?),
+    };
+
+    match parse_range(range, content.len() as u64) {
+        Some((start, end)) => Ok(response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, content.len()),
+            )
+            .body(content[start as usize..=end as usize].to_vec())?),
+        None => Ok(response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("Content-Range", format!("bytes */{}", content.len()))
+            .body(Vec::new())?),
+    }
+}
+
+/// A weak validator derived from the file's size and modification time.
+fn file_etag(metadata: &fs::Metadata, modified: std::time::SystemTime) -> String {
+    let secs = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    format!("W/\"{:x}-{:x}\"", secs, metadata.len())
+}
+
+fn is_not_modified(req: &Request<Vec<u8>>, etag: &str, modified: std::time::SystemTime) -> bool {
+    if let Some(if_none_match) = req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|v| v.to_str().ok())
+    {
+        return if_none_match
+            .split(',')
+            .any(|tag| tag.trim() == etag || tag.trim() == "*");
+    }
+
+    if let Some(since) = req
+        .headers()
+        .get("If-Modified-Since")
+        .and_then(|v| v.to_str().ok())
+    {
+        if let Ok(since) = httpdate::parse_http_date(since) {
+            return modified <= since;
         }
+    }
+
+    false
+}
+
+/// Parses a single-range `Range: bytes=...` header into an inclusive
+/// `(start, end)` byte range, supporting the open-ended `start-` and
+/// suffix `-len` forms. Returns `None` when the header is malformed or the
+/// range can't be satisfied.
+fn parse_range(header: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = header.strip_prefix("bytes=")?;
+    // Only a single range is supported; additional ranges are ignored.
+    let spec = spec.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+
+    if start.is_empty() {
+        let suffix_len: u64 = end.parse().ok()?;
+        if suffix_len == 0 || total == 0 {
+            return None;
+        }
+        let suffix_len = suffix_len.min(total);
+        return Some((total - suffix_len, total - 1));
+    }
+
+    let start: u64 = start.parse().ok()?;
+    if start >= total {
+        return None;
+    }
+
+    let end = if end.is_empty() {
+        total - 1
+    } else {
+        end.parse::<u64>().ok()?.min(total - 1)
     };
 
-    Ok(response.body(body)?)
+    if start > end {
+        return None;
+    }
+
+    Some((start, end))
 }
@@ -0,0 +1,235 @@
+//! RFC 6455 WebSocket framing, used once `process` upgrades a connection
+//! away from `HttpCodec`.
+
+use std::io;
+
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use http::header::AsHeaderName;
+use http::{HeaderMap, Method, Request};
+use sha1::{Digest, Sha1};
+
+use crate::codec::{BoxBody, Decoder, Encoder};
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// A fully-reassembled WebSocket message.
+pub enum Message {
+    Text(String),
+    Binary(Bytes),
+    Ping(Bytes),
+    Pong(Bytes),
+    Close,
+}
+
+/// True if `req` is a handshake request asking to upgrade to WebSocket.
+pub fn is_upgrade_request(req: &Request<Vec<u8>>) -> bool {
+    req.method() == Method::GET
+        && header_has_token(req.headers(), http::header::UPGRADE, "websocket")
+        && header_has_token(req.headers(), http::header::CONNECTION, "upgrade")
+}
+
+/// Computes `Sec-WebSocket-Accept` from the client's `Sec-WebSocket-Key`.
+pub fn accept_key(client_key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(client_key.as_bytes());
+    hasher.update(WS_GUID.as_bytes());
+    let digest = hasher.finalize();
+
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+fn header_has_token(headers: &HeaderMap, name: impl AsHeaderName, token: &str) -> bool {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+        .unwrap_or(false)
+}
+
+/// Decodes/encodes RFC 6455 frames over the same `HttpFramed` transport
+/// used for the initial HTTP handshake.
+#[derive(Default)]
+pub struct WsCodec {
+    state: FrameState,
+    fragment: Option<Fragment>,
+}
+
+#[derive(Clone, Copy, Default)]
+enum FrameState {
+    #[default]
+    Header,
+    Payload {
+        fin: bool,
+        opcode: u8,
+        mask: Option<[u8; 4]>,
+        len: u64,
+    },
+}
+
+struct Fragment {
+    opcode: u8,
+    buf: Vec<u8>,
+}
+
+impl Decoder for WsCodec {
+    type Item = Message;
+
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.state {
+                FrameState::Header => {
+                    if src.len() < 2 {
+                        return Ok(None);
+                    }
+
+                    let fin = src[0] & 0x80 != 0;
+                    let opcode = src[0] & 0x0F;
+                    let masked = src[1] & 0x80 != 0;
+                    let len7 = src[1] & 0x7F;
+
+                    let ext_len_bytes = match len7 {
+                        126 => 2,
+                        127 => 8,
+                        _ => 0,
+                    };
+                    let mask_bytes = if masked { 4 } else { 0 };
+                    let header_len = 2 + ext_len_bytes + mask_bytes;
+
+                    if src.len() < header_len {
+                        return Ok(None);
+                    }
+
+                    let len = match len7 {
+                        126 => u16::from_be_bytes(src[2..4].try_into().unwrap()) as u64,
+                        127 => u64::from_be_bytes(src[2..10].try_into().unwrap()),
+                        n => n as u64,
+                    };
+
+                    let mask = masked.then(|| {
+                        let offset = 2 + ext_len_bytes;
+                        let mut key = [0u8; 4];
+                        key.copy_from_slice(&src[offset..offset + 4]);
+                        key
+                    });
+
+                    let _ = src.split_to(header_len);
+
+                    self.state = FrameState::Payload {
+                        fin,
+                        opcode,
+                        mask,
+                        len,
+                    };
+                }
+                FrameState::Payload {
+                    fin,
+                    opcode,
+                    mask,
+                    len,
+                } => {
+                    let len = len as usize;
+                    if src.len() < len {
+                        return Ok(None);
+                    }
+
+                    let mut data = src.split_to(len).to_vec();
+                    if let Some(mask) = mask {
+                        for (i, byte) in data.iter_mut().enumerate() {
+                            *byte ^= mask[i % 4];
+                        }
+                    }
+
+                    self.state = FrameState::Header;
+
+                    match opcode {
+                        OP_CLOSE => return Ok(Some(Message::Close)),
+                        OP_PING => return Ok(Some(Message::Ping(Bytes::from(data)))),
+                        OP_PONG => return Ok(Some(Message::Pong(Bytes::from(data)))),
+                        OP_CONTINUATION => {
+                            let fragment = self.fragment.as_mut().ok_or_else(|| {
+                                io::Error::new(
+                                    io::ErrorKind::Other,
+                                    "continuation frame without a preceding fragment",
+                                )
+                            })?;
+                            fragment.buf.extend_from_slice(&data);
+
+                            if fin {
+                                let fragment = self.fragment.take().unwrap();
+                                return Ok(Some(finish_fragment(fragment)?));
+                            }
+                            // Otherwise keep accumulating the next frame.
+                        }
+                        OP_TEXT | OP_BINARY => {
+                            if fin {
+                                return Ok(Some(finish_fragment(Fragment {
+                                    opcode,
+                                    buf: data,
+                                })?));
+                            }
+                            self.fragment = Some(Fragment { opcode, buf: data });
+                        }
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::Other,
+                                "unsupported websocket opcode",
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn finish_fragment(fragment: Fragment) -> Result<Message, io::Error> {
+    match fragment.opcode {
+        OP_TEXT => String::from_utf8(fragment.buf)
+            .map(Message::Text)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid utf-8 in text frame")),
+        OP_BINARY => Ok(Message::Binary(Bytes::from(fragment.buf))),
+        _ => unreachable!("fragments are only started for text/binary frames"),
+    }
+}
+
+impl Encoder<Message> for WsCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<Option<BoxBody>, Self::Error> {
+        let (opcode, payload) = match item {
+            Message::Text(s) => (OP_TEXT, s.into_bytes()),
+            Message::Binary(b) => (OP_BINARY, b.to_vec()),
+            Message::Ping(b) => (OP_PING, b.to_vec()),
+            Message::Pong(b) => (OP_PONG, b.to_vec()),
+            Message::Close => (OP_CLOSE, Vec::new()),
+        };
+
+        // Server-to-client frames are sent unmasked, per RFC 6455.
+        dst.extend_from_slice(&[0x80 | opcode]);
+
+        let len = payload.len();
+        if len < 126 {
+            dst.extend_from_slice(&[len as u8]);
+        } else if len <= u16::MAX as usize {
+            dst.extend_from_slice(&[126]);
+            dst.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            dst.extend_from_slice(&[127]);
+            dst.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        dst.extend_from_slice(&payload);
+
+        Ok(None)
+    }
+}